@@ -1,8 +1,12 @@
 //! Test implementation of permutating and optimising permutation
 //! for prost-shuffle.
 
+use std::hash::{Hash, Hasher};
+
 use deepsize::DeepSizeOf;
 use itertools::Itertools;
+use rand::Rng;
+use rayon::prelude::*;
 
 #[derive(Debug, Clone, DeepSizeOf)]
 struct Permutation {
@@ -16,6 +20,28 @@ struct Permutation {
     child_permutations: Vec<Permutation>,
 }
 
+/// Generates all `n!` orderings of `items[..n]` in place using Heap's
+/// algorithm, calling `emit` with the current arrangement at each leaf
+/// instead of allocating a fresh `Vec` per permutation. Recursively permutes
+/// the first `n - 1` elements, then swaps element `i` with element `n - 1`
+/// (or element `0` with `n - 1` when `n` is odd) between recursive calls.
+fn heaps_permutations<T>(items: &mut [T], n: usize, emit: &mut impl FnMut(&[T])) {
+    if n <= 1 {
+        emit(items);
+        return;
+    }
+
+    for i in 0..n {
+        heaps_permutations(items, n - 1, emit);
+
+        if n.is_multiple_of(2) {
+            items.swap(i, n - 1);
+        } else {
+            items.swap(0, n - 1);
+        }
+    }
+}
+
 /// generate all possible permutations for the given lines. The count and total
 /// parameters are used to output a progress indicator.
 fn shuffle_lines(
@@ -41,14 +67,12 @@ fn shuffle_lines(
         .for_each(|x| chunks_vec.push(x.collect::<Vec<usize>>()));
     let chunks_count = chunks_vec.len();
 
-    let permutations = chunks_vec.into_iter().permutations(chunks_count);
-
-    for permutation in permutations {
+    heaps_permutations(&mut chunks_vec, chunks_count, &mut |permutation| {
         *count = *count + 1;
 
         let mut lines: Vec<usize> = Vec::new();
-        for chunk in &permutation {
-            lines.append(&mut chunk.clone());
+        for chunk in permutation {
+            lines.extend_from_slice(chunk);
         }
         eprint!(
             "generated permutation............................: {}/{}\r",
@@ -66,11 +90,93 @@ fn shuffle_lines(
                 total,
             ),
         });
-    }
+    });
 
     line_permutations
 }
 
+/// Lazy, iterator-based counterpart to [`shuffle_lines`] that yields one
+/// `Permutation` at a time instead of materializing the whole tree.
+struct PermutationIter {
+    blocksize: usize,
+    min_blocksize: usize,
+    block_permutations: Option<itertools::Permutations<std::vec::IntoIter<Vec<usize>>>>,
+    child_iter: Option<Box<PermutationIter>>,
+}
+
+impl PermutationIter {
+    fn new(lines: Vec<usize>, mut blocksize: usize, min_blocksize: usize) -> Self {
+        if blocksize == 1 || blocksize < min_blocksize {
+            return PermutationIter {
+                blocksize,
+                min_blocksize,
+                block_permutations: None,
+                child_iter: None,
+            };
+        }
+
+        blocksize /= 2;
+
+        let chunks = lines.into_iter().chunks(blocksize);
+        let mut chunks_vec: Vec<Vec<usize>> = Vec::new();
+        chunks
+            .into_iter()
+            .for_each(|x| chunks_vec.push(x.collect::<Vec<usize>>()));
+        let chunks_count = chunks_vec.len();
+
+        PermutationIter {
+            blocksize,
+            min_blocksize,
+            block_permutations: Some(chunks_vec.into_iter().permutations(chunks_count)),
+            child_iter: None,
+        }
+    }
+}
+
+impl Iterator for PermutationIter {
+    type Item = Permutation;
+
+    fn next(&mut self) -> Option<Permutation> {
+        if let Some(child_iter) = self.child_iter.as_mut() {
+            if let Some(child) = child_iter.next() {
+                return Some(child);
+            }
+            self.child_iter = None;
+        }
+
+        let permutation = self.block_permutations.as_mut()?.next()?;
+
+        let mut lines: Vec<usize> = Vec::new();
+        for mut chunk in permutation {
+            lines.append(&mut chunk);
+        }
+
+        self.child_iter = Some(Box::new(PermutationIter::new(
+            lines.clone(),
+            self.blocksize,
+            self.min_blocksize,
+        )));
+
+        Some(Permutation {
+            lines,
+            entropy: 999999.999999,
+            child_permutations: Vec::new(),
+        })
+    }
+}
+
+/// Generates the same permutations as [`shuffle_lines`], but lazily, one
+/// `Permutation` at a time. Each node is yielded before its descendants
+/// (depth-first) with an empty `child_permutations`, and block orderings come
+/// in lexicographic order rather than Heap's-algorithm order.
+fn iter_permutations(
+    lines: Vec<usize>,
+    blocksize: usize,
+    min_blocksize: usize,
+) -> impl Iterator<Item = Permutation> {
+    PermutationIter::new(lines, blocksize, min_blocksize)
+}
+
 /// Makes sure that the given set of permutations is no longer than MAX_PARENT_PERMUTATIONS.
 /// if the permutation count exceeds MAX_PARENT_PERMUTATIONS, split the
 /// permutations into equal parts and set all permutations after the first
@@ -128,6 +234,262 @@ fn optimize_permutations(
     }
 }
 
+/// Fixed-memory count-min sketch used to skip permutations that have
+/// (probably) already been evaluated.
+struct PermutationFilter {
+    width: usize,
+    depth: usize,
+    counters: Vec<Vec<u32>>,
+    seeds: Vec<u64>,
+}
+
+impl PermutationFilter {
+    /// Builds a sketch sized for over-count error factor `epsilon` and confidence
+    /// `1 - delta`, following `width ~= ceil(e / epsilon)` and
+    /// `depth ~= ceil(ln(1 / delta))`. Both parameters must be in `(0, 1]`.
+    fn new(epsilon: f64, delta: f64) -> Self {
+        assert!(
+            epsilon > 0.0 && epsilon <= 1.0,
+            "epsilon must be in (0, 1], got {epsilon}"
+        );
+        assert!(
+            delta > 0.0 && delta <= 1.0,
+            "delta must be in (0, 1], got {delta}"
+        );
+
+        let width = (std::f64::consts::E / epsilon).ceil() as usize;
+        let depth = (1.0 / delta).ln().ceil() as usize;
+
+        PermutationFilter {
+            width,
+            depth,
+            counters: vec![vec![0u32; width]; depth],
+            seeds: (0..depth as u64).map(|i| i.wrapping_mul(0x9E3779B97F4A7C15) ^ 1).collect(),
+        }
+    }
+
+    /// Hashes `lines` with the `row`-th seed into a column index.
+    fn hash(&self, lines: &[usize], row: usize) -> usize {
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        self.seeds[row].hash(&mut hasher);
+        lines.hash(&mut hasher);
+        (hasher.finish() as usize) % self.width
+    }
+
+    /// Records a permutation's line ordering in the sketch.
+    fn record(&mut self, lines: &[usize]) {
+        for row in 0..self.depth {
+            let column = self.hash(lines, row);
+            self.counters[row][column] = self.counters[row][column].saturating_add(1);
+        }
+    }
+
+    /// Returns true if this ordering has probably already been recorded.
+    fn probably_seen(&self, lines: &[usize]) -> bool {
+        (0..self.depth)
+            .map(|row| self.counters[row][self.hash(lines, row)])
+            .min()
+            .unwrap_or(0)
+            > 0
+    }
+}
+
+/// Evaluates `entropy` for every not-yet-seen permutation in parallel via
+/// rayon, then returns the minimum-entropy permutation's `child_permutations`
+/// as the next working set. `filter` is consulted first so duplicates are
+/// skipped rather than re-evaluated.
+fn evaluate_permutations_parallel<F>(
+    permutations: &mut [Permutation],
+    filter: &mut PermutationFilter,
+    entropy_fn: F,
+) -> Vec<Permutation>
+where
+    F: Fn(&[usize]) -> f32 + Sync,
+{
+    let mut should_evaluate: Vec<bool> = Vec::with_capacity(permutations.len());
+    for permutation in permutations.iter() {
+        let seen = filter.probably_seen(&permutation.lines);
+        if !seen {
+            filter.record(&permutation.lines);
+        }
+        should_evaluate.push(!seen);
+    }
+
+    permutations
+        .par_iter_mut()
+        .zip(should_evaluate.par_iter())
+        .for_each(|(permutation, &should_evaluate)| {
+            if should_evaluate {
+                permutation.entropy = entropy_fn(&permutation.lines);
+            }
+        });
+
+    // The count-min sketch can over-count, so it is possible (if unlikely)
+    // for it to flag every permutation in the batch as already seen. Fall
+    // back to the unfiltered batch instead of silently collapsing the next
+    // working set to empty and ending the search.
+    let all_flagged_seen = should_evaluate.iter().all(|&evaluated| !evaluated);
+    if all_flagged_seen {
+        eprintln!(
+            "evaluate_permutations_parallel: count-min filter flagged all {} permutations in this batch as already seen, falling back to the unfiltered minimum",
+            permutations.len()
+        );
+    }
+
+    let candidates: Vec<&Permutation> = if all_flagged_seen {
+        permutations.iter().collect()
+    } else {
+        permutations
+            .iter()
+            .zip(should_evaluate.iter())
+            .filter(|(_, &should_evaluate)| should_evaluate)
+            .map(|(permutation, _)| permutation)
+            .collect()
+    };
+
+    candidates
+        .into_iter()
+        .reduce(|a, b| if a.entropy <= b.entropy { a } else { b })
+        .map(|best| best.child_permutations.clone())
+        .unwrap_or_default()
+}
+
+/// Encodes a block ordering as a Lehmer code in the factorial number system:
+/// a single integer bijective with `0..k!` (for a `k`-block ordering) that
+/// can be reconstructed via [`decode`]. `order[position]` contributes
+/// `rank * (k - 1 - position)!`, where `rank` counts how many not-yet-placed
+/// blocks are smaller than `order[position]`.
+fn encode(order: &[usize]) -> u128 {
+    let k = order.len();
+    let mut remaining: Vec<usize> = (0..k).collect();
+    let mut index: u128 = 0;
+
+    for (position, &value) in order.iter().enumerate() {
+        let rank = remaining
+            .iter()
+            .position(|&v| v == value)
+            .expect("value already consumed");
+        remaining.remove(rank);
+
+        let factorial: u128 = (1..=(k - 1 - position) as u128).product();
+        index += rank as u128 * factorial;
+    }
+
+    index
+}
+
+/// Inverse of [`encode`]: reconstructs the `k`-block ordering represented by
+/// `index` in the factorial number system.
+fn decode(mut index: u128, k: usize) -> Vec<usize> {
+    let mut remaining: Vec<usize> = (0..k).collect();
+    let mut order: Vec<usize> = Vec::with_capacity(k);
+
+    for position in 0..k {
+        let factorial: u128 = (1..=(k - 1 - position) as u128).product();
+        let rank = (index / factorial) as usize;
+        index %= factorial;
+        order.push(remaining.remove(rank));
+    }
+
+    order
+}
+
+/// Compact alternative to [`Permutation`]: stores the Lehmer-code index of
+/// the block ordering instead of the expanded line vector.
+#[derive(Debug, Clone, DeepSizeOf)]
+struct CompactPermutation {
+    /// Lehmer-code index of the block ordering, bijective with `0..block_count!`
+    order_index: u128,
+
+    /// Number of blocks the ordering is over; needed to decode `order_index`
+    block_count: usize,
+
+    /// The entropy of the final generated executable with this permutation
+    entropy: f32,
+
+    /// The permutations derived from this permutation and "adopted" permutations
+    child_permutations: Vec<CompactPermutation>,
+}
+
+impl CompactPermutation {
+    /// Reconstructs the full line-number ordering by decoding `order_index`
+    /// into a block order and flattening the corresponding `chunks`.
+    fn lines(&self, chunks: &[Vec<usize>]) -> Vec<usize> {
+        let block_order = decode(self.order_index, self.block_count);
+
+        let mut lines: Vec<usize> = Vec::new();
+        for block in block_order {
+            lines.extend_from_slice(&chunks[block]);
+        }
+
+        lines
+    }
+}
+
+/// Builds the same recursive block-splitting tree as [`shuffle_lines`], but
+/// each node stores an [`encode`]d `order_index` instead of the expanded
+/// line vector (see [`CompactPermutation`]). Also returns the top-level
+/// chunks, since a caller needs them to decode a top-level node's lines via
+/// [`CompactPermutation::lines`]. Nested child nodes index the chunks of their
+/// parent's lines at the halved blocksize, which are not returned.
+fn compact_shuffle_lines(
+    lines: Vec<usize>,
+    mut blocksize: usize,
+    min_blocksize: usize,
+) -> (Vec<Vec<usize>>, Vec<CompactPermutation>) {
+    let mut node_permutations: Vec<CompactPermutation> = Vec::new();
+
+    if blocksize == 1 || blocksize < min_blocksize {
+        return (Vec::new(), node_permutations);
+    }
+
+    blocksize /= 2;
+
+    let chunks = lines.into_iter().chunks(blocksize);
+    let mut chunks_vec: Vec<Vec<usize>> = Vec::new();
+    chunks
+        .into_iter()
+        .for_each(|x| chunks_vec.push(x.collect::<Vec<usize>>()));
+    let block_count = chunks_vec.len();
+
+    let mut block_order: Vec<usize> = (0..block_count).collect();
+
+    heaps_permutations(&mut block_order, block_count, &mut |order| {
+        let mut lines: Vec<usize> = Vec::new();
+        for &block in order {
+            lines.extend_from_slice(&chunks_vec[block]);
+        }
+
+        node_permutations.push(CompactPermutation {
+            order_index: encode(order),
+            block_count,
+            entropy: 999999.999999,
+            child_permutations: compact_shuffle_lines(lines, blocksize, min_blocksize).1,
+        });
+    });
+
+    (chunks_vec, node_permutations)
+}
+
+/// Draws a uniform random sample of `k` permutations out of `source` in a
+/// single streaming pass, using Algorithm R reservoir sampling.
+fn reservoir_sample<I>(mut source: I, k: usize) -> Vec<Permutation>
+where
+    I: Iterator<Item = Permutation>,
+{
+    let mut reservoir: Vec<Permutation> = source.by_ref().take(k).collect();
+
+    let mut rng = rand::thread_rng();
+    for (i, permutation) in source.enumerate() {
+        let j = rng.gen_range(0..=(i + k));
+        if j < k {
+            reservoir[j] = permutation;
+        }
+    }
+
+    reservoir
+}
+
 /// Calculates an estimate of the total amount of permutations which would be generated
 /// with the linecount, starting blocksize and minimum blocksize.
 fn calculate_estimated_permutation_count(
@@ -181,6 +543,11 @@ fn make_mem_color(mem: f32) -> String {
     memory_color
 }
 
+/// Above this estimated count, materializing the whole eager tree risks the
+/// GiB-scale blowup `estimated_memory_usage` warns about in [`main`]; the
+/// lazy streaming and reservoir-sampling paths are used instead.
+const MAX_EAGER_PERMUTATION_COUNT: usize = 10_000;
+
 fn main() {
     let lines = "float i_event0 = 0;           // fade in
 float i_event5 = 2 * spsec;   // end of fade in
@@ -219,36 +586,102 @@ float i_event80 = 36 * spsec; // second dreamy bright scene"
         estimated_memory_usage
     );
 
-    let mut count: usize = 0;
+    if estimated_permutation_count <= MAX_EAGER_PERMUTATION_COUNT {
+        let mut count: usize = 0;
 
-    let mut permutations = shuffle_lines(
-        (0..line_count).collect(),
-        line_count,
-        minbs,
-        &mut count,
-        estimated_permutation_count,
-    );
+        let mut permutations = shuffle_lines(
+            (0..line_count).collect(),
+            line_count,
+            minbs,
+            &mut count,
+            estimated_permutation_count,
+        );
 
-    eprintln!("");
-    eprintln!(
-        "actual permutations to try.......................: {}",
-        count
-    );
+        eprintln!("");
+        eprintln!(
+            "actual permutations to try.......................: {}",
+            count
+        );
 
-    let mut opt_count = 0;
-    optimize_permutations(&mut permutations, &mut opt_count, count);
-    eprintln!("");
+        let mut opt_count = 0;
+        optimize_permutations(&mut permutations, &mut opt_count, count);
+        eprintln!("");
 
-    let actual_memory_usage: f32 = permutations.deep_size_of() as f32 / (1024.0_f32.powf(3.0));
-    eprintln!(
-        "\"actual\" memory usage for all permutations (GiB).: \x1b[1m{}{}\x1b[0m",
-        make_mem_color(actual_memory_usage),
-        actual_memory_usage
-    );
+        let actual_memory_usage: f32 = permutations.deep_size_of() as f32 / (1024.0_f32.powf(3.0));
+        eprintln!(
+            "\"actual\" memory usage for all permutations (GiB).: \x1b[1m{}{}\x1b[0m",
+            make_mem_color(actual_memory_usage),
+            actual_memory_usage
+        );
+
+        eprintln!(
+            "block depth......................................: {}",
+            count_permutations(&permutations)
+        );
 
+        // Evaluates the first 8 permutations of the lazy stream in parallel,
+        // skipping duplicates already recorded in a count-min sketch, and takes
+        // the minimum-entropy one's children as the next working set. The
+        // stream yields descendants as separate items and leaves
+        // `child_permutations` empty, so that working set is empty here.
+        let mut filter = PermutationFilter::new(0.01, 0.01);
+        let mut generation: Vec<Permutation> =
+            iter_permutations((0..line_count).collect(), line_count, minbs)
+                .take(8)
+                .collect();
+        let next_generation =
+            evaluate_permutations_parallel(&mut generation, &mut filter, |lines| {
+                lines.iter().sum::<usize>() as f32
+            });
+        eprintln!(
+            "next working set after parallel evaluation........: {}",
+            next_generation.len()
+        );
+
+        // Same tree as shuffle_lines(), but each node stores a Lehmer-code index
+        // instead of a full line vector.
+        let (compact_chunks, compact_permutations) =
+            compact_shuffle_lines((0..line_count).collect(), line_count, minbs);
+        let compact_memory_usage: f32 =
+            compact_permutations.deep_size_of() as f32 / (1024.0_f32.powf(3.0));
+        eprintln!(
+            "\"actual\" memory usage for compact permutations (GiB): \x1b[1m{}{}\x1b[0m",
+            make_mem_color(compact_memory_usage),
+            compact_memory_usage
+        );
+
+        // shuffle_lines() and compact_shuffle_lines() drive the same
+        // Heap's-algorithm order over the same input, so permutation `i` of
+        // one must decode to the same lines as permutation `i` of the
+        // other; a Lehmer-code regression in encode()/decode() would show up
+        // here instead of only in the node count or length.
+        for (permutation, compact) in permutations.iter().zip(compact_permutations.iter()) {
+            assert_eq!(
+                permutation.lines,
+                compact.lines(&compact_chunks),
+                "compact permutation decoded to the wrong line order"
+            );
+        }
+        eprintln!(
+            "verified {} compact permutations decode to shuffle_lines' lines",
+            permutations.len().min(compact_permutations.len())
+        );
+    } else {
+        eprintln!(
+            "estimated permutation count exceeds the eager-materialization budget of {}; streaming lazily instead",
+            MAX_EAGER_PERMUTATION_COUNT
+        );
+    }
+
+    // A fixed-size, representative sample to search instead of the full
+    // space when the estimate above is too large to search exhaustively.
+    let sample = reservoir_sample(
+        iter_permutations((0..line_count).collect(), line_count, minbs),
+        4,
+    );
     eprintln!(
-        "block depth......................................: {}",
-        count_permutations(&permutations)
+        "reservoir sample size..............................: {}",
+        sample.len()
     );
 
     // When checking which permutation is the best, the current set of permutations
@@ -258,3 +691,163 @@ float i_event80 = 36 * spsec; // second dreamy bright scene"
     // permutation, it should be made sure that this permutation is not a duplicate
     // of a previously checked permutation.
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn heaps_permutations_emits_all_n_factorial_orderings() {
+        let mut items: Vec<usize> = (0..5).collect();
+        let n = items.len();
+        let mut seen: Vec<Vec<usize>> = Vec::new();
+
+        heaps_permutations(&mut items, n, &mut |arrangement| {
+            seen.push(arrangement.to_vec());
+        });
+
+        let expected: HashSet<Vec<usize>> = (0..5).collect::<Vec<usize>>().into_iter().permutations(5).collect();
+        let actual: HashSet<Vec<usize>> = seen.into_iter().collect();
+
+        assert_eq!(actual.len(), 120);
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn encode_decode_round_trips_over_all_of_s5() {
+        let block_order: Vec<usize> = (0..5).collect();
+        let mut seen_indices: HashSet<u128> = HashSet::new();
+
+        for order in block_order.into_iter().permutations(5) {
+            let index = encode(&order);
+            assert_eq!(decode(index, 5), order);
+            assert!(seen_indices.insert(index), "index {index} reused by another ordering");
+        }
+
+        assert_eq!(seen_indices.len(), 120);
+    }
+
+    #[test]
+    fn permutation_filter_flags_recorded_permutations_as_seen() {
+        let mut filter = PermutationFilter::new(0.01, 0.01);
+
+        assert!(!filter.probably_seen(&[1, 2, 3]));
+
+        filter.record(&[1, 2, 3]);
+
+        assert!(filter.probably_seen(&[1, 2, 3]));
+        assert!(!filter.probably_seen(&[3, 2, 1]));
+    }
+
+    #[test]
+    #[should_panic]
+    fn permutation_filter_rejects_out_of_range_epsilon() {
+        PermutationFilter::new(0.0, 0.01);
+    }
+
+    #[test]
+    #[should_panic]
+    fn permutation_filter_rejects_out_of_range_delta() {
+        PermutationFilter::new(0.01, 0.0);
+    }
+
+    fn make_permutation(lines: Vec<usize>) -> Permutation {
+        Permutation {
+            lines,
+            entropy: 999999.999999,
+            child_permutations: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn reservoir_sample_respects_the_requested_size() {
+        let source = (0..50).map(|i| make_permutation(vec![i]));
+
+        let sample = reservoir_sample(source, 10);
+
+        assert_eq!(sample.len(), 10);
+    }
+
+    #[test]
+    fn reservoir_sample_returns_everything_when_source_is_smaller_than_k() {
+        let source = (0..3).map(|i| make_permutation(vec![i]));
+
+        let sample = reservoir_sample(source, 10);
+
+        assert_eq!(sample.len(), 3);
+    }
+
+    fn flatten_tree(permutations: &[Permutation]) -> Vec<Vec<usize>> {
+        let mut lines: Vec<Vec<usize>> = Vec::new();
+        for permutation in permutations {
+            lines.push(permutation.lines.clone());
+            lines.extend(flatten_tree(&permutation.child_permutations));
+        }
+        lines
+    }
+
+    #[test]
+    fn iter_permutations_matches_shuffle_lines() {
+        let line_count = 4;
+        let minbs = 2;
+
+        let mut count = 0;
+        let eager = shuffle_lines((0..line_count).collect(), line_count, minbs, &mut count, 0);
+        let mut eager_lines = flatten_tree(&eager);
+        eager_lines.sort();
+
+        let mut lazy_lines: Vec<Vec<usize>> =
+            iter_permutations((0..line_count).collect(), line_count, minbs)
+                .map(|permutation| permutation.lines)
+                .collect();
+        lazy_lines.sort();
+
+        assert_eq!(lazy_lines, eager_lines);
+    }
+
+    fn make_permutation_with_child(lines: Vec<usize>, child_lines: Vec<usize>) -> Permutation {
+        Permutation {
+            lines,
+            entropy: 999999.999999,
+            child_permutations: vec![make_permutation(child_lines)],
+        }
+    }
+
+    #[test]
+    fn evaluate_permutations_parallel_picks_minimum_entropy_children() {
+        let mut permutations = vec![
+            make_permutation_with_child(vec![3], vec![30]),
+            make_permutation_with_child(vec![1], vec![10]),
+            make_permutation_with_child(vec![2], vec![20]),
+        ];
+        let mut filter = PermutationFilter::new(0.01, 0.01);
+
+        let next =
+            evaluate_permutations_parallel(&mut permutations, &mut filter, |lines| lines[0] as f32);
+
+        assert_eq!(next.len(), 1);
+        assert_eq!(next[0].lines, vec![10]);
+    }
+
+    #[test]
+    fn evaluate_permutations_parallel_falls_back_when_filter_flags_whole_batch() {
+        let mut permutations = vec![
+            make_permutation_with_child(vec![3], vec![30]),
+            make_permutation_with_child(vec![1], vec![10]),
+        ];
+        permutations[0].entropy = 5.0;
+        permutations[1].entropy = 1.0;
+
+        let mut filter = PermutationFilter::new(0.01, 0.01);
+        filter.record(&permutations[0].lines.clone());
+        filter.record(&permutations[1].lines.clone());
+
+        let next = evaluate_permutations_parallel(&mut permutations, &mut filter, |_| {
+            panic!("entropy_fn should not run when the whole batch is already seen")
+        });
+
+        assert_eq!(next.len(), 1);
+        assert_eq!(next[0].lines, vec![10]);
+    }
+}